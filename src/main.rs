@@ -1,12 +1,28 @@
 mod matrix;
 mod util;
 
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use matrix::{ColumnFilter, Matrix, MatrixData};
 use rayon::prelude::*;
-use std::path::{Path, PathBuf}; // Add rayon for parallel processing
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tar::Builder as TarBuilder;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+/// A shared destination for the converted CSVs. In the default mode each matrix
+/// is written to a sibling `.csv` file; the archive modes instead stream every
+/// matrix into a single `.zip` or `.tar.gz` deliverable. Because conversions
+/// run concurrently, the archive writers are guarded by a `Mutex`.
+enum Archive {
+    Zip(Mutex<ZipWriter<std::fs::File>>),
+    Tar(Mutex<TarBuilder<GzEncoder<std::fs::File>>>),
+}
 
 fn main() {
     let command_line_args = std::env::args().collect::<Vec<_>>();
-    
+
     if command_line_args.is_empty() {
         panic!("No command line arguments provided");
     }
@@ -15,14 +31,78 @@ fn main() {
         exit_with_error(&command_line_args[0]);
     }
 
-    let column_output = command_line_args[1].eq_ignore_ascii_case("-c");
+    // The `csv-to-mtx` subcommand runs the inverse conversion, reading a CSV and
+    // emitting a binary EMME matrix.
+    if command_line_args[1].eq_ignore_ascii_case("csv-to-mtx") {
+        if command_line_args.len() < 4 {
+            exit_with_error(&command_line_args[0]);
+        }
+        let input = &command_line_args[2];
+        let output = &command_line_args[3];
+        if let Err(e) = csv_to_mtx(input, output) {
+            eprintln!("Error converting {input} to {output}: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Parse the leading options. `-c` selects column output, `-z`/`-t` bundle
+    // the results into a single archive instead of sibling files.
+    let mut index = 1;
+    let mut column_output = false;
+    let mut archive_path: Option<(ArchiveKind, String)> = None;
+    let mut filter = ColumnFilter::default();
+    while index < command_line_args.len() {
+        let arg = &command_line_args[index];
+        if arg.eq_ignore_ascii_case("-c") {
+            column_output = true;
+            index += 1;
+        } else if arg.eq_ignore_ascii_case("--min") {
+            index += 1;
+            let Some(value) = command_line_args.get(index) else {
+                exit_with_error(&command_line_args[0]);
+            };
+            let Ok(min) = value.parse() else {
+                exit_with_error(&command_line_args[0]);
+            };
+            filter.min = Some(min);
+            index += 1;
+        } else if arg.eq_ignore_ascii_case("--zones") {
+            index += 1;
+            let Some(path) = command_line_args.get(index) else {
+                exit_with_error(&command_line_args[0]);
+            };
+            match load_zones(path) {
+                Ok(zones) => filter.zones = Some(zones),
+                Err(e) => {
+                    eprintln!("Error reading zones file {path}: {e}");
+                    std::process::exit(1);
+                }
+            }
+            index += 1;
+        } else if arg.eq_ignore_ascii_case("-z") || arg.eq_ignore_ascii_case("-t") {
+            let kind = if arg.eq_ignore_ascii_case("-z") {
+                ArchiveKind::Zip
+            } else {
+                ArchiveKind::Tar
+            };
+            index += 1;
+            let Some(path) = command_line_args.get(index) else {
+                exit_with_error(&command_line_args[0]);
+            };
+            archive_path = Some((kind, path.clone()));
+            index += 1;
+        } else {
+            break;
+        }
+    }
 
-    // If column_output is true, we expect at least 3 arguments (program name, -c, and at least one file)
-    if column_output && command_line_args.len() < 3 {
+    let files_from_command_line = &command_line_args[index..];
+    if files_from_command_line.is_empty() {
         exit_with_error(&command_line_args[0]);
     }
 
-    let files_from_command_line = &command_line_args[(if column_output {2} else { 1})..]; 
+    let root = scan_root(files_from_command_line);
     let files = match gather_files(files_from_command_line) {
         Some(files) => files,
         None => {
@@ -31,41 +111,292 @@ fn main() {
         }
     };
 
+    let archive = match archive_path {
+        Some((kind, path)) => match create_archive(kind, &path) {
+            Ok(archive) => Some(archive),
+            Err(e) => {
+                eprintln!("Error creating archive {path}: {e}");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
     // Process files in parallel
     files.par_iter().for_each(|path| {
-        let result = process_mtx(path, column_output);
+        let result = process_mtx(path, column_output, &root, archive.as_ref(), &filter);
         if let Err(e) = result {
             let file_path = path.display();
             eprintln!("Error processing file {file_path}: {e}");
         }
     });
+
+    if let Some(archive) = archive {
+        if let Err(e) = finish_archive(archive) {
+            eprintln!("Error finalizing archive: {e}");
+            std::process::exit(1);
+        }
+    }
 }
 
+/// The archive container requested on the command line.
+enum ArchiveKind {
+    Zip,
+    Tar,
+}
 
-fn exit_with_error(program_name : &str) {
-    eprintln!("Usage: {program_name} [-c] <input1.mtx(.gz) / *> [<intput2.mtx>]");
+fn exit_with_error(program_name : &str) -> ! {
+    eprintln!("Usage: {program_name} [-c] [-z <archive.zip> | -t <archive.tar.gz>] <input1.mtx(.gz) / *> [<intput2.mtx>]");
     eprintln!(" [-c] is optional and indicates that the output files will be column-oriented.");
+    eprintln!(" [-z]/[-t] is optional and bundles every CSV into a single zip / tar.gz archive.");
+    eprintln!(" [--min <f>] skips column cells below a magnitude, [--zones <file>] restricts to a zone subset.");
+    eprintln!("       {program_name} csv-to-mtx <input.csv> <output.mtx(.gz)>");
+    eprintln!(" reads a column (Origin,Destination,Value) or square CSV and writes a binary matrix.");
     std::process::exit(1);
 }
 
+/// Read a column-oriented or square CSV and write it back out as a binary EMME
+/// matrix. A gzip stream is produced when the output path ends with `.gz`.
+fn csv_to_mtx(input: &str, output: &str) -> std::io::Result<()> {
+    let contents = std::fs::read_to_string(input)?;
+    let matrix = if contents
+        .lines()
+        .next()
+        .is_some_and(|header| header.starts_with("Origin"))
+    {
+        parse_column_csv(&contents)?
+    } else {
+        parse_square_csv(&contents)?
+    };
+    matrix.to_emme_file(output, output.ends_with(".gz"))
+}
+
+/// Parse a column-oriented (`Origin,Destination,Value`) CSV into a dense matrix.
+/// The origin and destination zone sets become the index vectors; OD pairs not
+/// present in the CSV default to zero.
+fn parse_column_csv(contents: &str) -> std::io::Result<Matrix> {
+    let mut origins = BTreeSet::new();
+    let mut destinations = BTreeSet::new();
+    let mut triples = Vec::new();
+    for line in contents.lines().skip(1) {
+        if line.is_empty() {
+            continue;
+        }
+        let mut fields = line.split(',');
+        let origin = parse_zone(fields.next())?;
+        let destination = parse_zone(fields.next())?;
+        let value = parse_value(fields.next())?;
+        origins.insert(origin);
+        destinations.insert(destination);
+        triples.push((origin, destination, value));
+    }
+
+    let row_zones: Vec<u32> = origins.into_iter().collect();
+    let col_zones: Vec<u32> = destinations.into_iter().collect();
+    let rows = row_zones.len();
+    let cols = col_zones.len();
+
+    let row_positions = zone_positions(&row_zones);
+    let col_positions = zone_positions(&col_zones);
+
+    let mut data = vec![0.0f32; rows * cols];
+    for (origin, destination, value) in triples {
+        let row = row_positions[&origin];
+        let col = col_positions[&destination];
+        data[row * cols + col] = value;
+    }
+
+    Ok(Matrix {
+        data: MatrixData::F32(data),
+        rows,
+        cols,
+        indexes: vec![row_zones, col_zones],
+    })
+}
+
+/// Parse a square CSV (first row of destination zones, first column of origin
+/// zones) into a dense matrix.
+fn parse_square_csv(contents: &str) -> std::io::Result<Matrix> {
+    let mut lines = contents.lines();
+    let header = lines
+        .next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Empty CSV"))?;
+    let col_zones: Vec<u32> = header
+        .split(',')
+        .skip(1)
+        .map(|zone| parse_zone(Some(zone)))
+        .collect::<std::io::Result<_>>()?;
+    let cols = col_zones.len();
+
+    let mut row_zones = Vec::new();
+    let mut data = Vec::new();
+    for line in lines {
+        if line.is_empty() {
+            continue;
+        }
+        let mut fields = line.split(',');
+        row_zones.push(parse_zone(fields.next())?);
+        for _ in 0..cols {
+            data.push(parse_value(fields.next())?);
+        }
+    }
+
+    Ok(Matrix {
+        data: MatrixData::F32(data),
+        rows: row_zones.len(),
+        cols,
+        indexes: vec![row_zones, col_zones],
+    })
+}
+
+/// Build a zone id → position lookup from an ordered list of zone ids.
+fn zone_positions(zones: &[u32]) -> HashMap<u32, usize> {
+    zones
+        .iter()
+        .enumerate()
+        .map(|(position, zone)| (*zone, position))
+        .collect()
+}
+
+/// Parse a zone id field, mapping a missing or malformed value to an error.
+fn parse_zone(field: Option<&str>) -> std::io::Result<u32> {
+    field
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid zone id"))
+}
+
+/// Parse a cell value field, mapping a missing or malformed value to an error.
+fn parse_value(field: Option<&str>) -> std::io::Result<f32> {
+    field
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid cell value"))
+}
+
+/// Load a set of zone ids from a file. Ids may be separated by whitespace or
+/// commas, one or many per line.
+fn load_zones(path: &str) -> std::io::Result<HashSet<u32>> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut zones = HashSet::new();
+    for token in contents.split(|c: char| c.is_whitespace() || c == ',') {
+        if token.is_empty() {
+            continue;
+        }
+        let zone = token.parse().map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid zone id in zones file")
+        })?;
+        zones.insert(zone);
+    }
+    Ok(zones)
+}
+
+/// Create the requested archive, opening its backing file for writing.
+fn create_archive(kind: ArchiveKind, path: &str) -> std::io::Result<Archive> {
+    let file = std::fs::File::create(path)?;
+    Ok(match kind {
+        ArchiveKind::Zip => Archive::Zip(Mutex::new(ZipWriter::new(file))),
+        ArchiveKind::Tar => {
+            let encoder = GzEncoder::new(file, Compression::default());
+            Archive::Tar(Mutex::new(TarBuilder::new(encoder)))
+        }
+    })
+}
+
+/// Finalize the archive, flushing its central directory / trailer to disk.
+fn finish_archive(archive: Archive) -> std::io::Result<()> {
+    match archive {
+        Archive::Zip(writer) => {
+            let writer = writer.into_inner().unwrap();
+            writer.finish()?;
+        }
+        Archive::Tar(builder) => {
+            let builder = builder.into_inner().unwrap();
+            let encoder = builder.into_inner()?;
+            encoder.finish()?;
+        }
+    }
+    Ok(())
+}
+
 /// Process the mtx file
-fn process_mtx(path: &Path, column_output : bool) -> std::io::Result<()> {
-    let path = path
+fn process_mtx(
+    path: &Path,
+    column_output: bool,
+    root: &Path,
+    archive: Option<&Archive>,
+    filter: &ColumnFilter,
+) -> std::io::Result<()> {
+    let path_str = path
         .to_str()
         .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid path"))?;
-    let matrix = matrix::Matrix::from_emme_file(path)?;
-    let output_path = path.to_string() + ".csv";
-    let file = std::fs::File::create(output_path)?;
-    // Use a buffered stream
-    let mut file = std::io::BufWriter::new(file);
-    if column_output {
-        matrix.write_csv_column(&mut file)?;
-    } else {
-        matrix.write_csv_square(&mut file)?;
+    let matrix = matrix::Matrix::from_emme_file(path_str)?;
+
+    match archive {
+        // Archive mode: render the CSV into memory, then append it to the shared
+        // archive under its lock so concurrent conversions don't interleave.
+        Some(archive) => {
+            let mut buffer = Vec::new();
+            if column_output {
+                matrix.write_csv_column(&mut buffer, filter)?;
+            } else {
+                matrix.write_csv_square(&mut buffer)?;
+            }
+            let entry_name = archive_entry_name(path, root);
+            match archive {
+                Archive::Zip(writer) => {
+                    let mut writer = writer.lock().unwrap();
+                    let options = SimpleFileOptions::default()
+                        .compression_method(zip::CompressionMethod::Deflated);
+                    writer.start_file(entry_name, options)?;
+                    std::io::Write::write_all(&mut *writer, &buffer)?;
+                }
+                Archive::Tar(builder) => {
+                    let mut header = tar::Header::new_gnu();
+                    header.set_size(buffer.len() as u64);
+                    header.set_mode(0o644);
+                    header.set_cksum();
+                    let mut builder = builder.lock().unwrap();
+                    builder.append_data(&mut header, entry_name, buffer.as_slice())?;
+                }
+            }
+        }
+        // Default mode: drop a sibling `.csv` next to the input.
+        None => {
+            let output_path = path_str.to_string() + ".csv";
+            let file = std::fs::File::create(output_path)?;
+            // Use a buffered stream
+            let mut file = std::io::BufWriter::new(file);
+            if column_output {
+                matrix.write_csv_column(&mut file, filter)?;
+            } else {
+                matrix.write_csv_square(&mut file)?;
+            }
+        }
     }
     Ok(())
 }
 
+/// Build the archive entry name for an input, relative to the scanned root so
+/// the archive mirrors the source tree layout.
+fn archive_entry_name(path: &Path, root: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    format!("{}.csv", relative.display())
+}
+
+/// Determine the root directory the inputs were scanned from, used to name
+/// archive entries relative to it.
+fn scan_root(files_from_command_line: &[String]) -> PathBuf {
+    let first = &files_from_command_line[0];
+    if first.ends_with('*') {
+        return PathBuf::from(first.trim_end_matches('*'));
+    }
+    let path = PathBuf::from(first);
+    if path.is_dir() {
+        path
+    } else {
+        path.parent().map(PathBuf::from).unwrap_or_default()
+    }
+}
+
 /// Gather files from command line arguments
 /// If the first argument is "*", it will gather all .mtx and .gz files in the current directory.
 /// Otherwise, it will gather the files specified in the command line arguments.
@@ -76,7 +407,7 @@ fn process_mtx(path: &Path, column_output : bool) -> std::io::Result<()> {
 fn gather_files(files_from_command_line: &[String]) -> Option<Vec<PathBuf>> {
     let mut files = Vec::new();
 
-    for file in files_from_command_line.iter() 
+    for file in files_from_command_line.iter()
     {
         if files_from_command_line[0].ends_with("*") {
             // Explore all files with that given directory recursively
@@ -95,7 +426,7 @@ fn gather_files(files_from_command_line: &[String]) -> Option<Vec<PathBuf>> {
             else {
                 files.push(path);
             }
-        }       
+        }
     }
 
     Some(files)
@@ -116,7 +447,9 @@ fn filter_for_mtx(entry: Result<std::fs::DirEntry, std::io::Error>) -> Option<Pa
     let extension = path.extension()?.to_str()?;
     match extension {
         "mtx" => Some(path),
-        "gz" => {
+        // Compressed matrices keep the `.mtx` stem; the actual container is
+        // detected from the magic bytes when the file is opened.
+        "gz" | "zst" | "bz2" => {
             let stem = path.file_stem()?;
             match util::ends_with(stem, "mtx") {
                 true => Some(path),
@@ -150,7 +483,7 @@ fn explore_directory_recursive(dir_path: &Path, files: &mut Vec<PathBuf>) {
         };
 
         let path = entry.path();
-        
+
         if path.is_dir() {
             // Recursively explore subdirectories
             explore_directory_recursive(&path, files);
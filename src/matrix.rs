@@ -1,14 +1,152 @@
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use bzip2::read::BzDecoder;
 use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{self, BufReader};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
 use std::path::Path;
 
 use crate::util::Reader;
 
+/// Open `path` and wrap it in a [`Reader`] whose compression is detected from
+/// the leading magic bytes rather than the file name: gzip is `1F 8B`, zstd is
+/// `28 B5 2F FD`, bzip2 is the ASCII "BZh", and anything else is treated as a
+/// plain EMME file.
+fn open_reader(path: &Path) -> io::Result<Reader<BufReader<File>>> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0u8; 4];
+    {
+        let peek = reader.fill_buf()?;
+        let len = peek.len().min(magic.len());
+        magic[..len].copy_from_slice(&peek[..len]);
+    }
+
+    Ok(if magic[0] == 0x1F && magic[1] == 0x8B {
+        Reader::Gzip(GzDecoder::new(reader))
+    } else if magic == [0x28, 0xB5, 0x2F, 0xFD] {
+        Reader::Zstd(zstd::Decoder::with_buffer(reader)?)
+    } else if &magic[..3] == b"BZh" {
+        Reader::Bzip2(BzDecoder::new(reader))
+    } else {
+        Reader::Plain(reader)
+    })
+}
+
+/// The on-disk byte width of each element for a given EMME `data_type` tag, or
+/// None for an unknown type.
+fn data_type_size(data_type: u32) -> Option<usize> {
+    match data_type {
+        1 => Some(std::mem::size_of::<f32>()),
+        2 => Some(std::mem::size_of::<f64>()),
+        3 => Some(std::mem::size_of::<i32>()),
+        4 => Some(std::mem::size_of::<i64>()),
+        _ => None,
+    }
+}
+
+/// The element payload of a matrix, tagged by the EMME `data_type` header field.
+///
+/// EMME encodes the element type as `1 = float32`, `2 = float64`, `3 = int32`
+/// and `4 = int64`; the variant we build mirrors whatever the header declares so
+/// that non-float matrices are no longer silently reinterpreted as `f32`.
+pub enum MatrixData {
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+    I32(Vec<i32>),
+    I64(Vec<i64>),
+}
+
+impl MatrixData {
+    /// The EMME `data_type` tag for this variant (`1 = float32`, `2 = float64`,
+    /// `3 = int32`, `4 = int64`).
+    fn data_type(&self) -> u32 {
+        match self {
+            MatrixData::F32(_) => 1,
+            MatrixData::F64(_) => 2,
+            MatrixData::I32(_) => 3,
+            MatrixData::I64(_) => 4,
+        }
+    }
+
+    /// Write the payload to `writer` in row-major order, little-endian.
+    fn write_payload(&self, writer: &mut dyn io::Write) -> io::Result<()> {
+        match self {
+            MatrixData::F32(data) => {
+                for value in data {
+                    writer.write_f32::<LittleEndian>(*value)?;
+                }
+            }
+            MatrixData::F64(data) => {
+                for value in data {
+                    writer.write_f64::<LittleEndian>(*value)?;
+                }
+            }
+            MatrixData::I32(data) => {
+                for value in data {
+                    writer.write_i32::<LittleEndian>(*value)?;
+                }
+            }
+            MatrixData::I64(data) => {
+                for value in data {
+                    writer.write_i64::<LittleEndian>(*value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single cell value, yielded when iterating over a row.
+pub enum MatrixValue {
+    F32(f32),
+    F64(f64),
+    I32(i32),
+    I64(i64),
+}
+
+impl MatrixValue {
+    /// The absolute magnitude of the value as an `f64`, used to compare against
+    /// a threshold regardless of the underlying element type.
+    fn magnitude(&self) -> f64 {
+        match self {
+            MatrixValue::F32(value) => (*value as f64).abs(),
+            MatrixValue::F64(value) => value.abs(),
+            MatrixValue::I32(value) => (*value as f64).abs(),
+            MatrixValue::I64(value) => (*value as f64).abs(),
+        }
+    }
+
+    /// Write the value to the given stream using the CSV representation for its
+    /// type: floating point values are fixed to five decimals, integers are
+    /// written verbatim.
+    fn write_csv(&self, writer: &mut dyn io::Write) -> io::Result<()> {
+        match self {
+            MatrixValue::F32(value) => write!(writer, "{value:.5}"),
+            MatrixValue::F64(value) => write!(writer, "{value:.5}"),
+            MatrixValue::I32(value) => write!(writer, "{value}"),
+            MatrixValue::I64(value) => write!(writer, "{value}"),
+        }
+    }
+}
+
+/// Options that restrict what the column writer emits. Both are optional; the
+/// defaults emit every cell.
+#[derive(Default)]
+pub struct ColumnFilter {
+    /// Skip cells whose absolute value is below this threshold.
+    pub min: Option<f64>,
+    /// Restrict output to origin and destination zone ids in this set,
+    /// intersected against the matrix index vectors.
+    pub zones: Option<HashSet<u32>>,
+}
+
 /// A struct that represents a matrix
 pub struct Matrix {
-    pub data: Vec<f32>,
+    pub data: MatrixData,
     pub rows: usize,
     pub cols: usize,
     pub indexes: Vec<Vec<u32>>,
@@ -24,14 +162,7 @@ impl Matrix {
     /// * A Result containing the Matrix if successful, or an io::Error if there was an issue reading the file
     pub fn from_emme_file(file_path: &str) -> io::Result<Matrix> {
         let path = Path::new(file_path);
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-
-        let mut reader = if file_path.ends_with(".gz") {
-            Reader::Gzip(GzDecoder::new(reader))
-        } else {
-            Reader::Plain(reader)
-        };
+        let mut reader = open_reader(path)?;
 
         let magic_number = reader.read_u32::<LittleEndian>()?;
 
@@ -40,8 +171,8 @@ impl Matrix {
         }
 
         let _version = reader.read_u32::<LittleEndian>()?;
-        // float32 = 1, float64 = 2, int32 = 3, int64 = 4, but we are going to assume float32
-        let _data_type = reader.read_u32::<LittleEndian>()?;
+        // float32 = 1, float64 = 2, int32 = 3, int64 = 4
+        let data_type = reader.read_u32::<LittleEndian>()?;
         let dimensions = reader.read_u32::<LittleEndian>()? as usize;
 
         // There should be 2 dimensions with indexes are are the same length and contain the same values
@@ -67,9 +198,22 @@ impl Matrix {
             indexes.push(data);
         }
 
-        // Read the data data payload
+        // Read the data payload, dispatching on the declared element type. The
+        // generic `read_into_vector` sizes itself via `size_of::<T>()`, so each
+        // variant reads the right number of bytes for its element width.
         let size = index_length[0] * index_length[1];
-        let data: Vec<f32> = reader.read_into_vector(size)?;
+        let data = match data_type {
+            1 => MatrixData::F32(reader.read_into_vector(size)?),
+            2 => MatrixData::F64(reader.read_into_vector(size)?),
+            3 => MatrixData::I32(reader.read_into_vector(size)?),
+            4 => MatrixData::I64(reader.read_into_vector(size)?),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid data type",
+                ))
+            }
+        };
 
         let rows = index_length[0];
         let cols = index_length[1];
@@ -88,15 +232,43 @@ impl Matrix {
     /// * `row` - The row index
     ///
     /// # Returns
-    /// * A Option containing the values at the given row or None if the indexes are out
-    pub fn get_row(&self, row: usize) -> Option<&[f32]> {
-        if row < self.rows {
-                let start = row * self.cols;
-                let end = start + self.cols;
-                Some(&self.data[start..end])
-        } else {
-            None
+    /// * An Option containing an iterator over the values at the given row, or
+    ///   None if the row index is out of range. The iterator yields a
+    ///   [`MatrixValue`] tagged with the matrix's element type.
+    pub fn get_row(&self, row: usize) -> Option<Box<dyn Iterator<Item = MatrixValue> + '_>> {
+        if row >= self.rows {
+            return None;
         }
+        let start = row * self.cols;
+        let end = start + self.cols;
+        let iter: Box<dyn Iterator<Item = MatrixValue>> = match &self.data {
+            MatrixData::F32(data) => Box::new(data[start..end].iter().map(|v| MatrixValue::F32(*v))),
+            MatrixData::F64(data) => Box::new(data[start..end].iter().map(|v| MatrixValue::F64(*v))),
+            MatrixData::I32(data) => Box::new(data[start..end].iter().map(|v| MatrixValue::I32(*v))),
+            MatrixData::I64(data) => Box::new(data[start..end].iter().map(|v| MatrixValue::I64(*v))),
+        };
+        Some(iter)
+    }
+
+    /// Get a single cell value by its physical row and column position.
+    ///
+    /// # Arguments
+    /// * `row` - The physical row position
+    /// * `col` - The physical column position
+    ///
+    /// # Returns
+    /// * An Option containing the value, or None if either position is out of range
+    pub fn get_value(&self, row: usize, col: usize) -> Option<MatrixValue> {
+        if row >= self.rows || col >= self.cols {
+            return None;
+        }
+        let offset = row * self.cols + col;
+        Some(match &self.data {
+            MatrixData::F32(data) => MatrixValue::F32(data[offset]),
+            MatrixData::F64(data) => MatrixValue::F64(data[offset]),
+            MatrixData::I32(data) => MatrixValue::I32(data[offset]),
+            MatrixData::I64(data) => MatrixValue::I64(data[offset]),
+        })
     }
 
     /// Create a new matrix that has all of the same values as the given matrix
@@ -124,31 +296,285 @@ impl Matrix {
             })?;
 
             // Write the row values
-            for item in row_data.iter() {
-                write!(writer, ",{item:.5}")?;
+            for item in row_data {
+                write!(writer, ",")?;
+                item.write_csv(writer)?;
             }
             writeln!(writer)?;
         }
         Ok(())
     }
 
-    pub fn write_csv_column(&self, writer: &mut dyn io::Write) -> io::Result<()> {
+    /// Encode the matrix back into a binary EMME `.mtx` file, the inverse of
+    /// [`Matrix::from_emme_file`].
+    ///
+    /// # Arguments
+    /// * `file_path` - A string slice that holds the path to write to
+    /// * `compress` - When true the output is wrapped in a gzip stream (for a `.mtx.gz` file)
+    ///
+    /// # Returns
+    /// * A Result if the write was successful, or an io::Error if there was an issue writing the file
+    pub fn to_emme_file(&self, file_path: &str, compress: bool) -> io::Result<()> {
+        let file = File::create(file_path)?;
+        let writer = BufWriter::new(file);
+        if compress {
+            let mut writer = GzEncoder::new(writer, Compression::default());
+            self.write_emme(&mut writer)?;
+            writer.finish()?;
+            Ok(())
+        } else {
+            let mut writer = writer;
+            self.write_emme(&mut writer)?;
+            writer.flush()
+        }
+    }
+
+    /// Write the binary EMME representation to any stream: the magic number, a
+    /// version, the `data_type` tag, `dimensions = 2`, the two index lengths,
+    /// the two index arrays, then the dense payload in row-major order.
+    fn write_emme(&self, writer: &mut dyn io::Write) -> io::Result<()> {
+        writer.write_u32::<LittleEndian>(0xC4D4F1B2)?;
+        writer.write_u32::<LittleEndian>(1)?;
+        writer.write_u32::<LittleEndian>(self.data.data_type())?;
+        writer.write_u32::<LittleEndian>(2)?;
+
+        writer.write_u32::<LittleEndian>(self.rows as u32)?;
+        writer.write_u32::<LittleEndian>(self.cols as u32)?;
+
+        for index in self.indexes.iter() {
+            for zone in index.iter() {
+                writer.write_u32::<LittleEndian>(*zone)?;
+            }
+        }
+
+        self.data.write_payload(writer)
+    }
+
+    pub fn write_csv_column(
+        &self,
+        writer: &mut dyn io::Write,
+        filter: &ColumnFilter,
+    ) -> io::Result<()> {
         let col_indexes = &self.indexes[0];
         let row_indexes = &self.indexes[1];
-        let number_of_columns = col_indexes.len();
+        // Precompute the physical column positions to keep so the inner loop
+        // only touches retained columns.
+        let retained_cols: Vec<usize> = match &filter.zones {
+            Some(zones) => (0..col_indexes.len())
+                .filter(|&col| zones.contains(&col_indexes[col]))
+                .collect(),
+            None => (0..col_indexes.len()).collect(),
+        };
         // Write the header
         writeln!(writer, "Origin,Destination,Value")?;
         for (row_index, row) in row_indexes.iter().enumerate() {
-            // Write the row index
-            let row_data = self.get_row(row_index).ok_or_else(|| {
-                io::Error::new(io::ErrorKind::InvalidData, "Invalid row index")
-            })?;
-            for col_index in 0..number_of_columns {
-                let value = row_data[col_index];
+            // Skip origins outside the requested zone subset.
+            if let Some(zones) = &filter.zones {
+                if !zones.contains(row) {
+                    continue;
+                }
+            }
+            for &col_index in retained_cols.iter() {
+                let value = self.get_value(row_index, col_index).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "Invalid cell index")
+                })?;
+                // Skip cells below the threshold, when one is set.
+                if let Some(min) = filter.min {
+                    if value.magnitude() < min {
+                        continue;
+                    }
+                }
                 let col = col_indexes[col_index];
-                writeln!(writer, "{row},{col},{value:.5}")?;
+                write!(writer, "{row},{col},")?;
+                value.write_csv(writer)?;
+                writeln!(writer)?;
             }
         }
         Ok(())
     }
 }
+
+/// A lazy reader over an EMME matrix that parses only the header and the two
+/// index vectors, keeping the dense payload on disk. Individual cells and rows
+/// are fetched on demand by seeking to their byte offset, so a handful of
+/// origin→destination lookups do not require materializing the whole
+/// `rows * cols` payload.
+///
+/// Random access is only efficient on plain files, whose [`Reader`] supports
+/// absolute seeks. Compressed containers can only seek forward, so this reader
+/// requires lookups against a compressed source to be issued in monotonically
+/// increasing offset order; an out-of-order lookup returns an error rather than
+/// silently rescanning.
+pub struct MatrixReader {
+    reader: Reader<BufReader<File>>,
+    data_type: u32,
+    element_size: usize,
+    cols: usize,
+    /// Byte offset of the first payload element.
+    payload_start: u64,
+    /// Next byte offset the underlying reader will read from.
+    position: u64,
+    /// Maps an origin zone id to its physical row position.
+    row_positions: HashMap<u32, usize>,
+    /// Maps a destination zone id to its physical column position.
+    col_positions: HashMap<u32, usize>,
+    /// Whether the source supports absolute (backwards) seeking.
+    seekable: bool,
+}
+
+impl MatrixReader {
+    /// Open an EMME matrix for random access, reading its header and index
+    /// vectors but not its payload.
+    ///
+    /// # Arguments
+    /// * `file_path` - A string slice that holds the path to the file
+    ///
+    /// # Returns
+    /// * A Result containing the reader if successful, or an io::Error if the header is invalid
+    pub fn open(file_path: &str) -> io::Result<MatrixReader> {
+        let path = Path::new(file_path);
+        let mut reader = open_reader(path)?;
+
+        let magic_number = reader.read_u32::<LittleEndian>()?;
+        if magic_number != 0xC4D4F1B2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid header"));
+        }
+
+        let _version = reader.read_u32::<LittleEndian>()?;
+        let data_type = reader.read_u32::<LittleEndian>()?;
+        let dimensions = reader.read_u32::<LittleEndian>()? as usize;
+
+        if dimensions != 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid dimensions",
+            ));
+        }
+
+        let element_size = data_type_size(data_type)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid data type"))?;
+
+        let mut index_length: [usize; 2] = [0; 2];
+        for index in index_length.iter_mut().take(dimensions) {
+            *index = reader.read_u32::<LittleEndian>()? as usize;
+        }
+
+        let mut index_vectors: Vec<Vec<u32>> = Vec::with_capacity(dimensions);
+        for index in index_length.iter() {
+            let data: Vec<u32> = reader.read_into_vector(*index)?;
+            index_vectors.push(data);
+        }
+
+        let rows = index_length[0];
+        let cols = index_length[1];
+
+        // header fields (magic, version, data_type, dimensions) + the two index
+        // lengths + the two index arrays, then the dense payload begins.
+        let payload_start = (16 + 8 + (rows + cols) * std::mem::size_of::<u32>()) as u64;
+
+        let row_positions = zone_positions(&index_vectors[0]);
+        let col_positions = zone_positions(&index_vectors[1]);
+        let seekable = matches!(reader, Reader::Plain(_));
+
+        Ok(MatrixReader {
+            reader,
+            data_type,
+            element_size,
+            cols,
+            payload_start,
+            position: payload_start,
+            row_positions,
+            col_positions,
+            seekable,
+        })
+    }
+
+    /// Fetch the value at a single origin→destination pair.
+    ///
+    /// # Arguments
+    /// * `origin_zone` - The origin zone id
+    /// * `dest_zone` - The destination zone id
+    ///
+    /// # Returns
+    /// * The value at that cell, or an io::Error if either zone is unknown or the seek failed
+    pub fn get_cell(&mut self, origin_zone: u32, dest_zone: u32) -> io::Result<MatrixValue> {
+        let row = *self.row_positions.get(&origin_zone).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Unknown origin zone")
+        })?;
+        let col = *self.col_positions.get(&dest_zone).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Unknown destination zone")
+        })?;
+        let offset = self.payload_start + ((row * self.cols + col) * self.element_size) as u64;
+        self.seek_to(offset)?;
+        self.read_value()
+    }
+
+    /// Fetch every value in the row for the given origin zone.
+    ///
+    /// # Arguments
+    /// * `origin_zone` - The origin zone id
+    ///
+    /// # Returns
+    /// * A vector with one value per destination column, or an io::Error if the zone is unknown
+    pub fn get_row_by_zone(&mut self, origin_zone: u32) -> io::Result<Vec<MatrixValue>> {
+        let row = *self.row_positions.get(&origin_zone).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Unknown origin zone")
+        })?;
+        let offset = self.payload_start + ((row * self.cols) * self.element_size) as u64;
+        self.seek_to(offset)?;
+        let mut values = Vec::with_capacity(self.cols);
+        for _ in 0..self.cols {
+            values.push(self.read_value()?);
+        }
+        Ok(values)
+    }
+
+    /// Position the underlying reader at `offset`. Plain files seek directly;
+    /// compressed files can only move forward, so a backwards seek is rejected.
+    fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        if self.seekable {
+            self.reader.seek(SeekFrom::Start(offset))?;
+        } else {
+            if offset < self.position {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Compressed matrices only support monotonically increasing lookups",
+                ));
+            }
+            if offset > self.position {
+                self.reader
+                    .seek(SeekFrom::Current((offset - self.position) as i64))?;
+            }
+        }
+        self.position = offset;
+        Ok(())
+    }
+
+    /// Read a single element of the matrix's declared type, advancing the
+    /// tracked position.
+    fn read_value(&mut self) -> io::Result<MatrixValue> {
+        let value = match self.data_type {
+            1 => MatrixValue::F32(self.reader.read_f32::<LittleEndian>()?),
+            2 => MatrixValue::F64(self.reader.read_f64::<LittleEndian>()?),
+            3 => MatrixValue::I32(self.reader.read_i32::<LittleEndian>()?),
+            4 => MatrixValue::I64(self.reader.read_i64::<LittleEndian>()?),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid data type",
+                ))
+            }
+        };
+        self.position += self.element_size as u64;
+        Ok(value)
+    }
+}
+
+/// Build a zone id → physical position lookup from an index vector.
+fn zone_positions(index: &[u32]) -> HashMap<u32, usize> {
+    index
+        .iter()
+        .enumerate()
+        .map(|(position, zone)| (*zone, position))
+        .collect()
+}
@@ -1,23 +1,31 @@
+use bzip2::read::BzDecoder;
 use flate2::read::GzDecoder;
-use std::io::{self, Read, Seek};
+use std::io::{self, BufRead, Read, Seek};
 
-/// An internal representation of a reader that can read from a plain file or a gzip file
-pub enum Reader<R: Read> {
+/// An internal representation of a reader that can read from a plain file or a
+/// compressed stream. The concrete container is detected from the leading magic
+/// bytes of the source, not from the file name, so a mislabelled file is still
+/// decoded correctly.
+pub enum Reader<R: BufRead> {
     Plain(R),
     Gzip(GzDecoder<R>),
+    Zstd(zstd::Decoder<'static, R>),
+    Bzip2(BzDecoder<R>),
 }
 
 #[doc(hidden)]
-impl<R: Read> Read for Reader<R> {
+impl<R: BufRead> Read for Reader<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match self {
             Reader::Plain(r) => r.read(buf),
             Reader::Gzip(r) => r.read(buf),
+            Reader::Zstd(r) => r.read(buf),
+            Reader::Bzip2(r) => r.read(buf),
         }
     }
 }
 
-impl<R: Read> Reader<R> {
+impl<R: BufRead> Reader<R> {
     /// Read the given number of elements into a new vector
     ///
     /// # Arguments
@@ -63,50 +71,59 @@ impl<R: Read> Reader<R> {
 }
 
 #[doc(hidden)]
-impl<R: Seek + Read> Seek for Reader<R> {
+impl<R: BufRead + Seek> Seek for Reader<R> {
     fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
         match self {
             Reader::Plain(r) => r.seek(pos),
-            Reader::Gzip(r) => {
-                // Since you can't directly seek on a GzDecoder, we need to read through the data
-                // until we reach the desired position.
-                match pos {
-                    io::SeekFrom::Start(_) => Err(io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        "Cannot seek to an absolute position in a Gzip file",
-                    )),
-                    io::SeekFrom::End(_) => Err(io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        "Cannot seek from the end of a Gzip file",
-                    )),
-                    io::SeekFrom::Current(pos) => {
-                        if pos < 0 {
-                            return Err(io::Error::new(
-                                io::ErrorKind::InvalidInput,
-                                "Cannot seek to a negative position in a GZip file",
-                            ));
-                        }
-                        let pos = pos as usize;
-                        // Create a small fixed sized buffer of 4kb and iteratively read from the GzDecoder
-                        // until we reach the desired position.
-                        const MAX_SIZE: usize = 4096;
-                        let mut buffer = [0; MAX_SIZE];
-                        let mut total_read: usize = 0;
-                        loop {
-                            let remaining = pos - total_read;
-                            let read = r.read(&mut buffer[..min(remaining, MAX_SIZE)])?;
-                            if read == 0 {
-                                break;
-                            }
-                            total_read += read;
-                            if total_read >= pos {
-                                break;
-                            }
-                        }
-                        Ok(0)
-                    }
+            // The compressed decoders expose no random access, so we emulate a
+            // monotonically forward `SeekFrom::Current` by reading and
+            // discarding bytes until we reach the target.
+            Reader::Gzip(r) => seek_forward(r, pos),
+            Reader::Zstd(r) => seek_forward(r, pos),
+            Reader::Bzip2(r) => seek_forward(r, pos),
+        }
+    }
+}
+
+/// Emulate a forward seek over a stream that cannot seek by reading and
+/// discarding bytes. Only `SeekFrom::Current` with a non-negative offset is
+/// supported; anything else is rejected.
+#[doc(hidden)]
+fn seek_forward<R: Read>(reader: &mut R, pos: io::SeekFrom) -> io::Result<u64> {
+    match pos {
+        io::SeekFrom::Start(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Cannot seek to an absolute position in a compressed file",
+        )),
+        io::SeekFrom::End(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Cannot seek from the end of a compressed file",
+        )),
+        io::SeekFrom::Current(pos) => {
+            if pos < 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Cannot seek to a negative position in a compressed file",
+                ));
+            }
+            let pos = pos as usize;
+            // Create a small fixed sized buffer of 4kb and iteratively read from the decoder
+            // until we reach the desired position.
+            const MAX_SIZE: usize = 4096;
+            let mut buffer = [0; MAX_SIZE];
+            let mut total_read: usize = 0;
+            loop {
+                let remaining = pos - total_read;
+                let read = reader.read(&mut buffer[..min(remaining, MAX_SIZE)])?;
+                if read == 0 {
+                    break;
+                }
+                total_read += read;
+                if total_read >= pos {
+                    break;
                 }
             }
+            Ok(0)
         }
     }
 }
@@ -128,8 +145,8 @@ fn min(a: usize, b: usize) -> usize {
 /// use crate::util::ends_with;
 /// fn example() {
 ///    let stem = OsStr::new("example.mtx");
-///   let suffix = "mtx";   
-///   assert_eq!(ends_with(stem, suffix), true);    
+///   let suffix = "mtx";
+///   assert_eq!(ends_with(stem, suffix), true);
 pub fn ends_with(file_name: &std::ffi::OsStr, suffix: &str) -> bool {
     file_name.to_str()
         .is_some_and(|s| s.ends_with(suffix))